@@ -43,16 +43,16 @@
 //! somebody's code, or an attacker is trying to improperly encode data to
 //! evade security checks.
 //!
-//! If you have a use case for lossy conversion to UTF-8, or conversion
-//! from mixed UTF-8/CESU-8 data, please feel free to submit a pull request
-//! for `from_cesu8_lossy_permissive` with appropriate behavior.
+//! If you have a use case for lossy conversion to UTF-8, or conversion from
+//! mixed UTF-8/CESU-8 data, see `from_cesu8_lossy` and
+//! `from_cesu8_lossy_permissive`.
 //!
 //! ### Java and U+0000, and other variants
 //!
 //! Java uses the CESU-8 encoding as described above, but with one
 //! difference: The null character U+0000 is represented as an overlong
-//! UTF-8 sequence.  This is not currently supported by this library, but
-//! pull requests to add `from_java_cesu8` and `to_java_cesu8` are welcome.
+//! UTF-8 sequence.  This is supported by `from_java_cesu8` and
+//! `to_java_cesu8`.
 //!
 //! ### Surrogate pairs and UTF-8
 //!
@@ -87,10 +87,13 @@
 #![feature(str_utf16)]
 
 use std::borrow::Cow;
+use std::cmp;
 use std::error::Error;
 use std::fmt;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::ptr;
 use std::result::Result;
-use std::slice;
 use std::str::{from_utf8, from_utf8_unchecked};
 use unicode::utf8_char_width;
 
@@ -101,9 +104,30 @@ const CONT_MASK: u8 = 0b0011_1111u8;
 /// Value of the tag bits (tag mask is !CONT_MASK) of a continuation byte.
 const TAG_CONT_U8: u8 = 0b1000_0000u8;
 
-/// The CESU-8 data could not be decoded as valid UTF-8 data.
-#[derive(Clone, Copy, Debug)]
-pub struct Cesu8DecodingError;
+/// The CESU-8 data could not be decoded as valid UTF-8 data.  This carries
+/// the position at which decoding failed, modelled after
+/// `std::str::Utf8Error`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Cesu8DecodingError {
+    valid_up_to: usize,
+    error_len: Option<usize>,
+}
+
+impl Cesu8DecodingError {
+    /// Returns the index in the input up to which valid CESU-8 was verified.
+    ///
+    /// The bytes before this index form a valid prefix which could be
+    /// decoded and handed to the caller before reporting the error.
+    pub fn valid_up_to(&self) -> usize { self.valid_up_to }
+
+    /// Returns the number of bytes, starting at
+    /// [`valid_up_to`](#method.valid_up_to), that were consumed before the
+    /// sequence was rejected, or `None` if the input ended unexpectedly in
+    /// the middle of a sequence.  When this is more than one, the count
+    /// includes the byte that caused the rejection, which may itself begin
+    /// a valid sequence.
+    pub fn error_len(&self) -> Option<usize> { self.error_len }
+}
 
 impl Error for Cesu8DecodingError {
     fn description(&self) -> &str { "decoding error" }
@@ -112,7 +136,8 @@ impl Error for Cesu8DecodingError {
 
 impl fmt::Display for Cesu8DecodingError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "could not convert CESU-8 data to UTF-8")
+        write!(f, "could not convert CESU-8 data to UTF-8 \
+                   (invalid sequence at index {})", self.valid_up_to)
     }
 }
 
@@ -135,19 +160,125 @@ impl fmt::Display for Cesu8DecodingError {
 ///            from_cesu8(data).unwrap());
 /// ```
 pub fn from_cesu8(bytes: &[u8]) -> Result<Cow<str>, Cesu8DecodingError> {
+    decode(bytes, false)
+}
+
+/// Convert Java's Modified UTF-8 data to a Rust string, re-encoding only if
+/// necessary.  This is identical to [`from_cesu8`](fn.from_cesu8.html),
+/// except that the two-byte sequence `0xC0 0x80` is accepted and decoded as
+/// U+0000.  A plain `0x00` byte is still passed through as an ASCII NUL,
+/// matching the behavior of JNI's `GetStringUTFChars`.
+///
+/// ```
+/// use std::borrow::Cow;
+/// use cesu8::from_java_cesu8;
+///
+/// // Java encodes an embedded NUL as the overlong sequence 0xC0 0x80.
+/// let data = &[0x4D, 0xC0, 0x80, 0x4E];
+/// assert_eq!(Cow::Borrowed("M\u{0}N"),
+///            from_java_cesu8(data).unwrap());
+/// ```
+pub fn from_java_cesu8(bytes: &[u8]) -> Result<Cow<str>, Cesu8DecodingError> {
+    decode(bytes, true)
+}
+
+/// Shared implementation of [`from_cesu8`](fn.from_cesu8.html) and
+/// [`from_java_cesu8`](fn.from_java_cesu8.html).  When `java` is true, the
+/// overlong NUL sequence `0xC0 0x80` is treated as U+0000.
+fn decode(bytes: &[u8], java: bool) -> Result<Cow<str>, Cesu8DecodingError> {
     match from_utf8(bytes) {
         Ok(str) => Ok(Cow::Borrowed(str)),
         _ => {
             let mut decoded = Vec::with_capacity(bytes.len());
-            if decode_from_iter(&mut decoded, &mut bytes.iter()) {
-                // We can remove this assertion if we trust our decoder.
-                assert!(from_utf8(&decoded[..]).is_ok());
-                Ok(Cow::Owned(unsafe { String::from_utf8_unchecked(decoded) }))
-            } else {
-                Err(Cesu8DecodingError)
+            try!(decode_into(&mut decoded, bytes, java));
+            // We can remove this assertion if we trust our decoder.
+            assert!(from_utf8(&decoded[..]).is_ok());
+            Ok(Cow::Owned(unsafe { String::from_utf8_unchecked(decoded) }))
+        }
+    }
+}
+
+/// The UTF-8 encoding of the replacement character U+FFFD.
+const REPLACEMENT: &'static [u8] = &[0xEF, 0xBF, 0xBD];
+
+/// Convert CESU-8 data to a Rust string, replacing any ill-formed sequence
+/// with the replacement character U+FFFD instead of returning an error.
+///
+/// Following the same maximal-subpart rule as `std::str::from_utf8`, exactly
+/// one U+FFFD is emitted per ill-formed unit rather than one per byte.  When
+/// the input is already valid UTF-8 it is borrowed without allocating.
+///
+/// ```
+/// use std::borrow::Cow;
+/// use cesu8::from_cesu8_lossy;
+///
+/// assert_eq!(Cow::Borrowed("a\u{FFFD}b"),
+///            from_cesu8_lossy(&[0x61, 0xFF, 0x62]));
+/// ```
+pub fn from_cesu8_lossy(bytes: &[u8]) -> Cow<str> {
+    decode_lossy(bytes, false)
+}
+
+/// Like [`from_cesu8_lossy`](fn.from_cesu8_lossy.html), but additionally
+/// tolerant of input that mixes UTF-8 and CESU-8: a genuine four-byte UTF-8
+/// astral sequence is accepted alongside six-byte CESU-8 surrogate pairs,
+/// and an unpaired surrogate half is passed through as its own U+FFFD rather
+/// than derailing the rest of the decode.
+pub fn from_cesu8_lossy_permissive(bytes: &[u8]) -> Cow<str> {
+    decode_lossy(bytes, true)
+}
+
+/// Shared implementation of the lossy decoders.  When `permissive` is true,
+/// valid four-byte UTF-8 sequences are accepted verbatim.
+fn decode_lossy(bytes: &[u8], permissive: bool) -> Cow<str> {
+    // Keep the borrow-if-possible optimization for already-clean input.
+    if let Ok(str) = from_utf8(bytes) {
+        return Cow::Borrowed(str);
+    }
+
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut rest = bytes;
+    while !rest.is_empty() {
+        match decode_into(&mut out, rest, false) {
+            // `decode_into` has appended the whole decoded prefix.
+            Ok(()) => break,
+            Err(err) => {
+                // The good prefix of `rest` is already in `out`; `tail`
+                // begins at the ill-formed sequence.
+                let tail = &rest[err.valid_up_to()..];
+                if permissive && is_utf8_astral(tail) {
+                    // A real four-byte UTF-8 astral character: keep it.
+                    out.extend_from_slice(&tail[..4]);
+                    rest = &tail[4..];
+                    continue;
+                }
+                out.extend_from_slice(REPLACEMENT);
+                // Replace the maximal subpart: when more than one byte was
+                // consumed it was the final byte that failed, so leave it for
+                // reprocessing.
+                match err.error_len() {
+                    None => break,
+                    // A lone low surrogate is one three-byte unit.
+                    Some(2) if tail[0] == 0xED && tail[1] >= 0xB0 &&
+                        tail[1] <= 0xBF && tail.len() >= 3 &&
+                        tail[2] & !CONT_MASK == TAG_CONT_U8 =>
+                        rest = &tail[3..],
+                    // A complete high surrogate without its low partner is
+                    // one unit; whatever follows it is decoded afresh.
+                    Some(n) if n > 3 => rest = &tail[3..],
+                    Some(1) => rest = &tail[1..],
+                    Some(n) => rest = &tail[n - 1..],
+                }
             }
         }
-    }    
+    }
+    Cow::Owned(unsafe { String::from_utf8_unchecked(out) })
+}
+
+/// Return true if `bytes` begins with a valid four-byte UTF-8 sequence.
+fn is_utf8_astral(bytes: &[u8]) -> bool {
+    utf8_char_width(bytes[0]) == 4 && bytes.len() >= 4 &&
+        from_utf8(&bytes[..4]).is_ok()
 }
 
 #[test]
@@ -158,6 +289,10 @@ fn test_from_cesu8() {
     assert_eq!(Cow::Borrowed("M日\u{10401}"),
                from_cesu8(data).unwrap());
 
+    // A plain 0x00 is an ordinary ASCII NUL in both variants.
+    assert_eq!(Cow::Borrowed("\u{0}"), from_cesu8(&[0x00]).unwrap());
+    assert_eq!(Cow::Borrowed("\u{0}"), from_java_cesu8(&[0x00]).unwrap());
+
     // We used to have test data from the CESU-8 specification, but when we
     // worked it through manually, we got the wrong answer:
     // 
@@ -175,73 +310,163 @@ fn test_from_cesu8() {
     // specification, I decided to use a test character from ICU instead.
 }
 
-// Our internal decoder, based on Rust's is_utf8 implementation.
-fn decode_from_iter(decoded: &mut Vec<u8>, iter: &mut slice::Iter<u8>) -> bool {
-    macro_rules! err {
-        () => { return false }
-    }
-    macro_rules! next {
-        () => {
-            match iter.next() {
-                Some(a) => *a,
-                // We needed data, but there was none: error!
-                None => err!()
+#[test]
+fn test_dfa_decoder() {
+    // A long ASCII run exercises the word-at-a-time fast path, and is
+    // followed by a surrogate pair that the DFA must combine.
+    let mut data = vec![0x61; 40];
+    data.extend([0xED, 0xA0, 0x81, 0xED, 0xB0, 0x81].iter().cloned());
+    let mut expected = String::from_utf8(vec![0x61; 40]).unwrap();
+    expected.push('\u{10401}');
+    assert_eq!(Cow::Borrowed(&expected[..]), from_cesu8(&data).unwrap());
+
+    // A lone low surrogate (0xED 0xB0..0xBF ...) is rejected.
+    assert!(from_cesu8(&[0xED, 0xB0, 0x81]).is_err());
+
+    // A high surrogate not followed by a low one is rejected.
+    assert!(from_cesu8(&[0xED, 0xA0, 0x81, 0x61]).is_err());
+}
+
+// The number of byte classes, which is also the width of a row in the DFA
+// transition table.  State IDs are stored pre-multiplied by this width so the
+// hot path can index `TRANSITION[state + class]` without a multiply.
+const CLASS_COUNT: u8 = 10;
+
+// The rejecting and accepting states, pre-multiplied by `CLASS_COUNT`.
+// `REJECT` is logical state 0 and `ACCEPT` is logical state 1.
+const REJECT: u8 = 0;
+const ACCEPT: u8 = CLASS_COUNT;
+
+// Maps each byte to one of the `CLASS_COUNT` classes understood by the DFA:
+//
+//   0  ASCII 0x00..=0x7F        5  two-byte lead 0xC2..=0xDF
+//   1  continuation 0x80..=0x8F 6  three-byte lead 0xE0
+//   2  continuation 0x90..=0x9F 7  three-byte lead 0xE1..=0xEC, 0xEE..=0xEF
+//   3  continuation 0xA0..=0xAF 8  three-byte lead 0xED
+//   4  continuation 0xB0..=0xBF 9  invalid (0xC0,0xC1, four-byte, 0xF5..=0xFF)
+//
+// CESU-8 has no four-byte forms, so every 0xF0..=0xFF lead is class 9.
+static BYTE_CLASS: [u8; 256] = [
+    // 0x00..=0x7F: ASCII
+    0,0,0,0,0,0,0,0, 0,0,0,0,0,0,0,0, 0,0,0,0,0,0,0,0, 0,0,0,0,0,0,0,0,
+    0,0,0,0,0,0,0,0, 0,0,0,0,0,0,0,0, 0,0,0,0,0,0,0,0, 0,0,0,0,0,0,0,0,
+    0,0,0,0,0,0,0,0, 0,0,0,0,0,0,0,0, 0,0,0,0,0,0,0,0, 0,0,0,0,0,0,0,0,
+    0,0,0,0,0,0,0,0, 0,0,0,0,0,0,0,0, 0,0,0,0,0,0,0,0, 0,0,0,0,0,0,0,0,
+    // 0x80..=0x8F, 0x90..=0x9F, 0xA0..=0xAF, 0xB0..=0xBF: continuations
+    1,1,1,1,1,1,1,1, 1,1,1,1,1,1,1,1, 2,2,2,2,2,2,2,2, 2,2,2,2,2,2,2,2,
+    3,3,3,3,3,3,3,3, 3,3,3,3,3,3,3,3, 4,4,4,4,4,4,4,4, 4,4,4,4,4,4,4,4,
+    // 0xC0..=0xCF: 0xC0,0xC1 invalid, rest two-byte leads
+    9,9,5,5,5,5,5,5, 5,5,5,5,5,5,5,5,
+    // 0xD0..=0xDF: two-byte leads
+    5,5,5,5,5,5,5,5, 5,5,5,5,5,5,5,5,
+    // 0xE0..=0xEF: three-byte leads (0xE0 and 0xED are special)
+    6,7,7,7,7,7,7,7, 7,7,7,7,7,8,7,7,
+    // 0xF0..=0xFF: no four-byte forms in CESU-8
+    9,9,9,9,9,9,9,9, 9,9,9,9,9,9,9,9,
+];
+
+// `TRANSITION[state + class]` yields the next state (also pre-multiplied).
+// Rows, in order of pre-multiplied state ID, are:
+//   0  REJECT       30 after 0xE0        60 high surrogate, last byte
+//   10 ACCEPT       40 after other 0xExx 70 need low-surrogate lead 0xED
+//   20 need 1 cont  50 after 0xED        80 after low 0xED  90 low last byte
+static TRANSITION: [u8; 100] = [
+    //  c0  c1  c2  c3  c4  c5  c6  c7  c8  c9
+        0,  0,  0,  0,  0,  0,  0,  0,  0,  0, // 0: REJECT
+        0,  0,  0,  0,  0, 20, 30, 40, 50,  0, // 10: ACCEPT (consume lead)
+        0, 10, 10, 10, 10,  0,  0,  0,  0,  0, // 20: need one continuation
+        0,  0,  0, 20, 20,  0,  0,  0,  0,  0, // 30: 0xE0, second must be A0..BF
+        0, 20, 20, 20, 20,  0,  0,  0,  0,  0, // 40: other 0xExx second byte
+        0, 20, 20, 60,  0,  0,  0,  0,  0,  0, // 50: 0xED (A0..AF -> surrogate)
+        0, 70, 70, 70, 70,  0,  0,  0,  0,  0, // 60: high surrogate, third byte
+        0,  0,  0,  0,  0,  0,  0,  0, 80,  0, // 70: need low surrogate 0xED
+        0,  0,  0,  0, 90,  0,  0,  0,  0,  0, // 80: low 0xED, second must be B0..BF
+        0, 10, 10, 10, 10,  0,  0,  0,  0,  0, // 90: low surrogate, third byte
+];
+
+// Our internal decoder, built around a Hoehrmann-style table-driven DFA with
+// a bulk ASCII fast path.  When `java` is true, the overlong sequence
+// 0xC0 0x80 is accepted as U+0000.
+fn decode_into(decoded: &mut Vec<u8>, bytes: &[u8], java: bool)
+               -> Result<(), Cesu8DecodingError> {
+    let len = bytes.len();
+    // A word with the high bit set in every byte, for the ASCII fast path.
+    let high_bits = (usize::MAX / 0xFF) * 0x80;
+    let word = mem::size_of::<usize>();
+
+    let mut i = 0;
+    while i < len {
+        // We are always at a sequence boundary (DFA in the ACCEPT state) here.
+        let first = bytes[i];
+
+        if first < 0x80 {
+            // Bulk ASCII fast path: copy a run of bytes below 0x80, testing a
+            // whole machine word at a time for any set high bit.
+            let run_start = i;
+            while i + word <= len {
+                let chunk = unsafe {
+                    ptr::read_unaligned(bytes.as_ptr().offset(i as isize)
+                                        as *const usize)
+                };
+                if chunk & high_bits != 0 { break; }
+                i += word;
+            }
+            while i < len && bytes[i] < 0x80 {
+                i += 1;
             }
+            decoded.extend_from_slice(&bytes[run_start..i]);
+            continue;
         }
-    }
-    macro_rules! next_cont {
-        () => {
-            {
-                let byte = next!();
-                if (byte) & !CONT_MASK == TAG_CONT_U8 { byte } else { err!() }
+
+        if java && first == 0xC0 {
+            // Java encodes U+0000 as the overlong sequence 0xC0 0x80.  Only
+            // 0x80 may follow; any other byte is a genuine overlong form.
+            if i + 1 >= len {
+                return Err(Cesu8DecodingError {
+                    valid_up_to: i, error_len: None });
+            }
+            if bytes[i + 1] != 0x80 {
+                return Err(Cesu8DecodingError {
+                    valid_up_to: i, error_len: Some(2) });
             }
+            decoded.push(0x00);
+            i += 2;
+            continue;
         }
-    }
 
-    loop {
-        let first = match iter.next() {
-            Some(&b) => b,
-            // We're at the end of the iterator and a codepoint boundary at
-            // the same time, so this string is valid.
-            None => return true
-        };
+        // Multi-byte sequence: drive the DFA until it accepts or rejects.
+        let start = i;
+        let mut state = ACCEPT;
+        loop {
+            if i >= len {
+                // The input ended in the middle of a sequence.
+                return Err(Cesu8DecodingError {
+                    valid_up_to: start, error_len: None });
+            }
+            let class = BYTE_CLASS[bytes[i] as usize];
+            state = TRANSITION[(state + class) as usize];
+            i += 1;
+            if state == REJECT {
+                return Err(Cesu8DecodingError {
+                    valid_up_to: start, error_len: Some(i - start) });
+            }
+            if state == ACCEPT {
+                break;
+            }
+        }
 
-        if first < 127 {
-            // Pass ASCII through directly.
-            decoded.push(first);
+        let unit = &bytes[start..i];
+        if unit.len() == 6 {
+            // A six-byte unit is a CESU-8 surrogate pair; combine it into a
+            // single four-byte UTF-8 sequence.
+            let s = dec_surrogates(unit[1], unit[2], unit[4], unit[5]);
+            decoded.extend(s.iter().cloned());
         } else {
-            let w = utf8_char_width(first);
-            let second = next_cont!();
-            match w {
-                // Two-byte sequences can be used directly.
-                2 => { decoded.extend([first, second].iter().cloned()); }
-                3 => {
-                    let third = next_cont!();
-                    match (first, second) {
-                        // These are valid UTF-8, so pass them through.
-                        (0xE0         , 0xA0 ... 0xBF) |
-                        (0xE1 ... 0xEC, 0x80 ... 0xBF) |
-                        (0xED         , 0x80 ... 0x9F) |
-                        (0xEE ... 0xEF, 0x80 ... 0xBF) => {
-                            decoded.extend([first, second, third].iter()
-                                               .cloned())
-                        }
-                        // First half a surrogate pair, so decode.
-                        (0xED         , 0xA0 ... 0xAF) => {
-                            if next!() != 0xED { err!() }
-                            let fifth = next_cont!();
-                            if fifth < 0xB0 || 0xBF < fifth { err!() }
-                            let sixth = next_cont!();
-                            let s = dec_surrogates(second, third, fifth, sixth);
-                            decoded.extend(s.iter().cloned());
-                        }
-                        _ => err!()
-                    }
-                }
-                _ => err!()
-            }
+            // Everything else is already valid UTF-8; pass it through.
+            decoded.extend_from_slice(unit);
         }
     }
+    Ok(())
 }
 
 /// Convert the two trailing bytes from a CESU-8 surrogate to a regular
@@ -286,40 +511,108 @@ fn dec_surrogates(second: u8, third: u8, fifth: u8, sixth: u8) -> [u8; 4] {
 ///            to_cesu8("\u{10401}"));
 /// ```
 pub fn to_cesu8(text: &str) -> Cow<[u8]> {
-    if is_valid_cesu8(text) {
+    encode(text, false)
+}
+
+/// Convert a Rust `&str` to Java's Modified UTF-8 bytes.  This is identical
+/// to [`to_cesu8`](fn.to_cesu8.html), except that embedded U+0000 code
+/// points are written as the overlong sequence `0xC0 0x80` instead of a
+/// single `0x00` byte.
+///
+/// ```
+/// use std::borrow::Cow;
+/// use cesu8::to_java_cesu8;
+///
+/// // An embedded NUL becomes the two-byte sequence 0xC0 0x80.
+/// assert_eq!(Cow::Borrowed(&[0x4D, 0xC0, 0x80, 0x4E][..]),
+///            to_java_cesu8("M\u{0}N"));
+/// ```
+pub fn to_java_cesu8(text: &str) -> Cow<[u8]> {
+    encode(text, true)
+}
+
+/// Shared implementation of [`to_cesu8`](fn.to_cesu8.html) and
+/// [`to_java_cesu8`](fn.to_java_cesu8.html).  When `java` is true, U+0000 is
+/// written as the overlong sequence `0xC0 0x80`.
+fn encode(text: &str, java: bool) -> Cow<[u8]> {
+    // Java can only borrow when there are no embedded NUL bytes to rewrite.
+    let borrowable = is_valid_cesu8(text) &&
+        (!java || !text.as_bytes().contains(&0));
+    if borrowable {
         Cow::Borrowed(text.as_bytes())
     } else {
         let bytes = text.as_bytes();
         let mut encoded = Vec::with_capacity(bytes.len() + bytes.len() >> 2);
-        let mut i = 0;
-        while i < bytes.len() {
-            let b = bytes[i];
-            if b < 128 {
-                // Pass ASCII through quickly.
+        encode_into(bytes, java, &mut encoded);
+        Cow::Owned(encoded)
+    }
+}
+
+/// Encode `bytes` (which must be valid UTF-8) as CESU-8, appending the result
+/// to `encoded`.  U+0000 is written as the overlong sequence `0xC0 0x80` when
+/// `java` is true.
+fn encode_into(bytes: &[u8], java: bool, encoded: &mut Vec<u8>) {
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        if b < 128 {
+            // Pass ASCII through quickly, rewriting NUL for Java.
+            if java && b == 0 {
+                encoded.extend([0xC0, 0x80].iter().cloned());
+            } else {
                 encoded.push(b);
-                i += 1;
+            }
+            i += 1;
+        } else {
+            // Figure out how many bytes we need for this character.
+            let w = utf8_char_width(b);
+            assert!(w <= 4);
+            assert!(i + w <= bytes.len());
+            if w != 4 {
+                // Pass through short UTF-8 sequences unmodified.
+                encoded.extend(bytes[i..i+w].iter().cloned());
             } else {
-                // Figure out how many bytes we need for this character.
-                let w = utf8_char_width(b);
-                assert!(w <= 4);
-                assert!(i + w <= bytes.len());
-                if w != 4 {
-                    // Pass through short UTF-8 sequences unmodified.
-                    encoded.extend(bytes[i..i+w].iter().cloned());
-                } else {
-                    // Encode 4-byte sequences as 6 bytes.
-                    let s = unsafe { from_utf8_unchecked(&bytes[i..i+w]) };
-                    for u in s.utf16_units() {
-                        encoded.extend(enc_surrogate(u).iter().cloned());
-                    }
+                // Encode 4-byte sequences as 6 bytes.
+                let s = unsafe { from_utf8_unchecked(&bytes[i..i+w]) };
+                for u in s.utf16_units() {
+                    encoded.extend(enc_surrogate(u).iter().cloned());
                 }
-                i += w;
             }
+            i += w;
         }
-        Cow::Owned(encoded)
     }
 }
 
+#[test]
+fn test_java_nul() {
+    // Round-trip an embedded NUL through the Java variant.
+    assert_eq!(Cow::Borrowed(&[0x4D, 0xC0, 0x80, 0x4E][..]),
+               to_java_cesu8("M\u{0}N"));
+    assert_eq!(Cow::Borrowed("M\u{0}N"),
+               from_java_cesu8(&[0x4D, 0xC0, 0x80, 0x4E]).unwrap());
+
+    // The standard variant leaves NUL as a single byte.
+    assert_eq!(Cow::Borrowed(&[0x00][..]), to_cesu8("\u{0}"));
+
+    // Overlong forms other than 0xC0 0x80 are still rejected.
+    assert!(from_java_cesu8(&[0xC0, 0x81]).is_err());
+}
+
+#[test]
+fn test_decoding_error_position() {
+    // A valid character followed by a truncated surrogate pair: the error
+    // points at the start of the bad sequence and reports end-of-input.
+    let data = &[0x4D, 0xED, 0xA0, 0x81, 0xED, 0xB0];
+    let err = from_cesu8(data).unwrap_err();
+    assert_eq!(err.valid_up_to(), 1);
+    assert_eq!(err.error_len(), None);
+
+    // A bad continuation byte reports the length consumed so far.
+    let err = from_cesu8(&[0x4D, 0xED, 0xA0, 0x81, 0xED, 0x00]).unwrap_err();
+    assert_eq!(err.valid_up_to(), 1);
+    assert_eq!(err.error_len(), Some(5));
+}
+
 /// Check whether a Rust string contains valid CESU-8 data.
 pub fn is_valid_cesu8(text: &str) -> bool {
     // We rely on the fact that Rust strings are guaranteed to be valid
@@ -334,8 +627,568 @@ pub fn is_valid_cesu8(text: &str) -> bool {
 /// Encode a single surrogate as CESU-8.
 fn enc_surrogate(surrogate: u16) -> [u8; 3] {
     assert!(0xD800 <= surrogate && surrogate <= 0xDFFF);
+    enc_bmp(surrogate)
+}
+
+/// Encode a single 16-bit code unit (a BMP scalar value or a bare surrogate
+/// half) as its three-byte CESU-8 form.
+fn enc_bmp(unit: u16) -> [u8; 3] {
     // 1110xxxx 10xxxxxx 10xxxxxx
-    [0b11100000  | ((surrogate & 0b11110000_00000000) >> 12) as u8,
-     TAG_CONT_U8 | ((surrogate & 0b00001111_11000000) >>  6) as u8,
-     TAG_CONT_U8 | ((surrogate & 0b00000000_00111111)      ) as u8]
+    [0b11100000  | ((unit & 0b11110000_00000000) >> 12) as u8,
+     TAG_CONT_U8 | ((unit & 0b00001111_11000000) >>  6) as u8,
+     TAG_CONT_U8 | ((unit & 0b00000000_00111111)      ) as u8]
+}
+
+/// Decode CESU-8 data directly to a vector of UTF-16 code units, without the
+/// intermediate `&str`.  Unlike [`from_cesu8`](fn.from_cesu8.html), surrogate
+/// halves are pushed individually rather than being recombined into a single
+/// astral code point, which is exactly what a JVM or JNI caller holding a
+/// `jchar[]` array needs.
+///
+/// ```
+/// use cesu8::from_cesu8_to_utf16;
+///
+/// // A 6-byte surrogate pair becomes two separate UTF-16 code units.
+/// let data = &[0xED, 0xA0, 0x81, 0xED, 0xB0, 0x81];
+/// assert_eq!(vec![0xD801, 0xDC01], from_cesu8_to_utf16(data).unwrap());
+/// ```
+pub fn from_cesu8_to_utf16(bytes: &[u8])
+                           -> Result<Vec<u16>, Cesu8DecodingError> {
+    let len = bytes.len();
+    let mut units = Vec::with_capacity(len);
+    let mut i = 0;
+    while i < len {
+        let start = i;
+        let first = bytes[i];
+        if first < 0x80 {
+            units.push(first as u16);
+            i += 1;
+            continue;
+        }
+        // CESU-8 code units never occupy more than three bytes.
+        let w = utf8_char_width(first);
+        if w != 2 && w != 3 {
+            return Err(Cesu8DecodingError { valid_up_to: start,
+                                            error_len: Some(1) });
+        }
+        if start + w > len {
+            return Err(Cesu8DecodingError { valid_up_to: start,
+                                            error_len: None });
+        }
+        let second = bytes[start + 1];
+        if second & !CONT_MASK != TAG_CONT_U8 {
+            return Err(Cesu8DecodingError { valid_up_to: start,
+                                            error_len: Some(2) });
+        }
+        if w == 2 {
+            if first < 0xC2 {
+                // Reject the 0xC0/0xC1 overlong forms rather than silently
+                // decoding an overlong NUL, matching `from_cesu8`.
+                return Err(Cesu8DecodingError { valid_up_to: start,
+                                                error_len: Some(1) });
+            }
+            let unit = ((first as u16 & 0x1F) << 6)
+                     | (second as u16 & CONT_MASK as u16);
+            units.push(unit);
+            i += 2;
+        } else {
+            let third = bytes[start + 2];
+            if third & !CONT_MASK != TAG_CONT_U8 {
+                return Err(Cesu8DecodingError { valid_up_to: start,
+                                                error_len: Some(3) });
+            }
+            // Reject overlong and otherwise-invalid three-byte forms, but
+            // accept every 0xED sequence so that surrogate halves survive.
+            match (first, second) {
+                (0xE0         , 0xA0 ... 0xBF) |
+                (0xE1 ... 0xEC, 0x80 ... 0xBF) |
+                (0xED         , 0x80 ... 0xBF) |
+                (0xEE ... 0xEF, 0x80 ... 0xBF) => {}
+                _ => return Err(Cesu8DecodingError { valid_up_to: start,
+                                                     error_len: Some(3) }),
+            }
+            let unit = ((first as u16 & 0x0F) << 12)
+                     | ((second as u16 & CONT_MASK as u16) << 6)
+                     | (third as u16 & CONT_MASK as u16);
+            units.push(unit);
+            i += 3;
+        }
+    }
+    Ok(units)
+}
+
+/// Encode a slice of UTF-16 code units directly as CESU-8, without the
+/// intermediate `&str`.  Because CESU-8 is a byte serialization of UTF-16,
+/// each code unit is emitted on its own and no surrogate pairing is needed:
+/// a lone or paired surrogate half is written verbatim as its three-byte
+/// form.
+///
+/// ```
+/// use cesu8::utf16_to_cesu8;
+///
+/// assert_eq!(vec![0xED, 0xA0, 0x81, 0xED, 0xB0, 0x81],
+///            utf16_to_cesu8(&[0xD801, 0xDC01]));
+/// ```
+pub fn utf16_to_cesu8(units: &[u16]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(units.len() * 3);
+    for &unit in units {
+        if unit < 0x80 {
+            encoded.push(unit as u8);
+        } else if unit < 0x800 {
+            encoded.push(0b11000000 | (unit >> 6) as u8);
+            encoded.push(TAG_CONT_U8 | (unit as u8 & CONT_MASK));
+        } else {
+            // Everything else, including bare surrogate halves, is three
+            // bytes.
+            encoded.extend(enc_bmp(unit).iter().cloned());
+        }
+    }
+    encoded
+}
+
+#[test]
+fn test_from_cesu8_lossy() {
+    // One replacement per ill-formed unit, surrounding bytes preserved.
+    assert_eq!(Cow::Borrowed("a\u{FFFD}b"),
+               from_cesu8_lossy(&[0x61, 0xFF, 0x62]));
+
+    // A well-formed surrogate pair still decodes to its astral character.
+    let data = &[0xED, 0xA0, 0x81, 0xED, 0xB0, 0x81];
+    assert_eq!(Cow::Borrowed("\u{10401}"), from_cesu8_lossy(data));
+
+    // Permissive mode accepts a real four-byte UTF-8 sequence mixed in with
+    // CESU-8, while a lone surrogate half collapses to a single U+FFFD.
+    let mixed = &[0xED, 0xA0, 0x81, 0xED, 0xB0, 0x81, 0xF0, 0x9F, 0x98, 0x80];
+    assert_eq!(Cow::Borrowed("\u{10401}\u{1F600}"),
+               from_cesu8_lossy_permissive(mixed));
+    assert_eq!(Cow::Borrowed("\u{FFFD}"),
+               from_cesu8_lossy_permissive(&[0xED, 0xA0, 0x81]));
+}
+
+#[test]
+fn test_utf16_roundtrip() {
+    // A surrogate pair stays split into two code units and round-trips.
+    let data = &[0xED, 0xA0, 0x81, 0xED, 0xB0, 0x81];
+    let units = from_cesu8_to_utf16(data).unwrap();
+    assert_eq!(vec![0xD801u16, 0xDC01], units);
+    assert_eq!(&data[..], &utf16_to_cesu8(&units)[..]);
+
+    // BMP and two-byte forms round-trip as well.
+    let units = from_cesu8_to_utf16("aé日".as_bytes()).unwrap();
+    assert_eq!(vec![0x61u16, 0xE9, 0x65E5], units);
+    assert_eq!("aé日".as_bytes(), &utf16_to_cesu8(&units)[..]);
+}
+
+/// Convert WTF-8 data (UTF-8 generalized to allow unpaired surrogate code
+/// points) to CESU-8.  Four-byte astral sequences become six-byte surrogate
+/// pairs, while an unpaired surrogate is preserved as its own three-byte
+/// form rather than being rejected as [`from_cesu8`](fn.from_cesu8.html)
+/// would.
+///
+/// Like WTF-8 itself, this conversion is not concatenation-safe: an isolated
+/// trailing high surrogate in one buffer will *not* combine with a leading
+/// low surrogate in the next.
+///
+/// ```
+/// use cesu8::wtf8_to_cesu8;
+///
+/// // An isolated high surrogate survives the round trip.
+/// let lone = &[0xED, 0xA0, 0x81];
+/// assert_eq!(lone.to_vec(), wtf8_to_cesu8(lone).unwrap());
+/// ```
+pub fn wtf8_to_cesu8(bytes: &[u8]) -> Result<Vec<u8>, Cesu8DecodingError> {
+    let len = bytes.len();
+    let mut out = Vec::with_capacity(len + len >> 2);
+    let mut i = 0;
+    while i < len {
+        let start = i;
+        let first = bytes[i];
+        if first < 0x80 {
+            out.push(first);
+            i += 1;
+            continue;
+        }
+        let w = utf8_char_width(first);
+        if w == 0 || start + w > len {
+            return Err(Cesu8DecodingError {
+                valid_up_to: start,
+                error_len: if w == 0 { Some(1) } else { None },
+            });
+        }
+        for k in 1..w {
+            if bytes[start + k] & !CONT_MASK != TAG_CONT_U8 {
+                return Err(Cesu8DecodingError { valid_up_to: start,
+                                                error_len: Some(k + 1) });
+            }
+        }
+        if w == 2 {
+            // Reject the 0xC0/0xC1 overlong forms.
+            if first < 0xC2 {
+                return Err(Cesu8DecodingError { valid_up_to: start,
+                                                error_len: Some(1) });
+            }
+            out.extend_from_slice(&bytes[start..start + 2]);
+        } else if w == 3 {
+            // Reject the overlong 0xE0 forms, but accept every other BMP
+            // lead (including 0xED so that bare surrogates survive).
+            let second = bytes[start + 1];
+            match (first, second) {
+                (0xE0         , 0xA0 ... 0xBF) |
+                (0xE1 ... 0xEF, 0x80 ... 0xBF) => {}
+                _ => return Err(Cesu8DecodingError {
+                    valid_up_to: start, error_len: Some(2) }),
+            }
+            out.extend_from_slice(&bytes[start..start + 3]);
+        } else {
+            // A four-byte astral scalar becomes a six-byte surrogate pair.
+            match from_utf8(&bytes[start..start + 4]) {
+                Ok(s) => {
+                    for u in s.utf16_units() {
+                        out.extend(enc_surrogate(u).iter().cloned());
+                    }
+                }
+                Err(_) => return Err(Cesu8DecodingError {
+                    valid_up_to: start, error_len: Some(4) }),
+            }
+        }
+        i += w;
+    }
+    Ok(out)
+}
+
+/// Convert CESU-8 data to WTF-8.  A six-byte surrogate pair becomes a single
+/// four-byte astral sequence, while an unpaired surrogate is preserved as its
+/// three-byte form rather than being rejected.
+///
+/// The combining is greedy and boundary-sensitive: a high surrogate that is
+/// *immediately* followed by a low surrogate is merged into one astral scalar
+/// (matching [`from_cesu8`](fn.from_cesu8.html)), but an isolated high
+/// surrogate at the end of the buffer stays isolated.
+pub fn cesu8_to_wtf8(bytes: &[u8]) -> Result<Vec<u8>, Cesu8DecodingError> {
+    let len = bytes.len();
+    let mut out = Vec::with_capacity(len);
+    let mut i = 0;
+    while i < len {
+        let start = i;
+        let first = bytes[i];
+        if first < 0x80 {
+            out.push(first);
+            i += 1;
+            continue;
+        }
+        let w = utf8_char_width(first);
+        if w != 2 && w != 3 {
+            return Err(Cesu8DecodingError { valid_up_to: start,
+                                            error_len: Some(1) });
+        }
+        if start + w > len {
+            return Err(Cesu8DecodingError { valid_up_to: start,
+                                            error_len: None });
+        }
+        for k in 1..w {
+            if bytes[start + k] & !CONT_MASK != TAG_CONT_U8 {
+                return Err(Cesu8DecodingError { valid_up_to: start,
+                                                error_len: Some(k + 1) });
+            }
+        }
+        if w == 2 {
+            // Reject the 0xC0/0xC1 overlong forms.
+            if first < 0xC2 {
+                return Err(Cesu8DecodingError { valid_up_to: start,
+                                                error_len: Some(1) });
+            }
+            out.extend_from_slice(&bytes[start..start + 2]);
+            i += 2;
+            continue;
+        }
+        let second = bytes[start + 1];
+        let third = bytes[start + 2];
+        match (first, second) {
+            // Ordinary BMP scalar values pass straight through.
+            (0xE0         , 0xA0 ... 0xBF) |
+            (0xE1 ... 0xEC, 0x80 ... 0xBF) |
+            (0xEE ... 0xEF, 0x80 ... 0xBF) |
+            (0xED         , 0x80 ... 0x9F) => {
+                out.extend_from_slice(&bytes[start..start + 3]);
+                i += 3;
+            }
+            // A high surrogate: combine with an immediately following low
+            // surrogate, otherwise keep it isolated.
+            (0xED         , 0xA0 ... 0xAF) => {
+                if start + 6 <= len && bytes[start + 3] == 0xED
+                    && bytes[start + 4] >= 0xB0 && bytes[start + 4] <= 0xBF
+                    && bytes[start + 5] & !CONT_MASK == TAG_CONT_U8 {
+                    let s = dec_surrogates(second, third,
+                                           bytes[start + 4], bytes[start + 5]);
+                    out.extend_from_slice(&s);
+                    i += 6;
+                } else {
+                    out.extend_from_slice(&bytes[start..start + 3]);
+                    i += 3;
+                }
+            }
+            // An isolated low surrogate also survives.
+            (0xED         , 0xB0 ... 0xBF) => {
+                out.extend_from_slice(&bytes[start..start + 3]);
+                i += 3;
+            }
+            _ => return Err(Cesu8DecodingError { valid_up_to: start,
+                                                 error_len: Some(3) }),
+        }
+    }
+    Ok(out)
+}
+
+#[test]
+fn test_wtf8_roundtrip() {
+    // An astral scalar is a four-byte WTF-8 sequence but a six-byte CESU-8
+    // surrogate pair.
+    let wtf8 = "\u{10401}".as_bytes();
+    let cesu8 = &[0xED, 0xA0, 0x81, 0xED, 0xB0, 0x81];
+    assert_eq!(cesu8.to_vec(), wtf8_to_cesu8(wtf8).unwrap());
+    assert_eq!(wtf8.to_vec(), cesu8_to_wtf8(cesu8).unwrap());
+
+    // An isolated high surrogate is preserved in both directions, and is
+    // not combined across a buffer boundary.
+    let lone = &[0xED, 0xA0, 0x81];
+    assert_eq!(lone.to_vec(), wtf8_to_cesu8(lone).unwrap());
+    assert_eq!(lone.to_vec(), cesu8_to_wtf8(lone).unwrap());
+
+    // A high surrogate immediately followed by a low one is still combined.
+    assert_eq!("\u{10401}".as_bytes().to_vec(),
+               cesu8_to_wtf8(cesu8).unwrap());
+}
+
+/// The number of input bytes read from the underlying reader at a time.
+const CHUNK_LEN: usize = 8 * 1024;
+
+/// Wrap a decoding error as an `io::Error` of kind `InvalidData`.
+fn decoding_io_error(err: Cesu8DecodingError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+/// An adapter that decodes CESU-8 read from an underlying [`Read`] and yields
+/// plain UTF-8 bytes.  A multi-byte sequence that straddles a chunk boundary
+/// (for example a 6-byte surrogate pair, or the 2-byte Java NUL) is buffered
+/// and resumed on the next fill, so the caller need not align its reads.
+///
+/// A malformed sequence surfaces as an `io::Error` of kind `InvalidData`
+/// wrapping a [`Cesu8DecodingError`](struct.Cesu8DecodingError.html) whose
+/// `valid_up_to` is relative to the whole stream.
+pub struct Cesu8Reader<R: Read> {
+    inner: R,
+    java: bool,
+    // Bytes read from `inner` that do not yet form a complete sequence.
+    partial: Vec<u8>,
+    // Decoded UTF-8 bytes waiting to be handed to the caller.
+    decoded: Vec<u8>,
+    // Offset into `decoded` of the next unread byte.
+    pos: usize,
+    // Number of input bytes consumed before `partial`, for positioning.
+    base: usize,
+    // Set once the underlying reader has signalled end-of-input.
+    eof: bool,
+    // A decoding error to report once the decoded prefix has been drained.
+    error: Option<Cesu8DecodingError>,
+}
+
+impl<R: Read> Cesu8Reader<R> {
+    /// Create a reader which decodes standard CESU-8 from `inner`.
+    pub fn new(inner: R) -> Cesu8Reader<R> {
+        Cesu8Reader {
+            inner: inner, java: false, partial: Vec::new(),
+            decoded: Vec::new(), pos: 0, base: 0, eof: false, error: None,
+        }
+    }
+
+    /// Create a reader which decodes Java's Modified UTF-8 from `inner`.
+    pub fn new_java(inner: R) -> Cesu8Reader<R> {
+        Cesu8Reader { java: true, ..Cesu8Reader::new(inner) }
+    }
+
+    // Ensure `decoded[pos..]` is non-empty, unless we have reached a clean
+    // end of input.  Reports a decoding error once any preceding decoded
+    // bytes have been drained.
+    fn fill(&mut self) -> io::Result<()> {
+        while self.pos >= self.decoded.len() {
+            if let Some(err) = self.error.take() {
+                return Err(decoding_io_error(err));
+            }
+            if self.eof && self.partial.is_empty() {
+                return Ok(());
+            }
+            if !self.eof {
+                let mut chunk = [0u8; CHUNK_LEN];
+                let n = try!(self.inner.read(&mut chunk));
+                if n == 0 {
+                    self.eof = true;
+                } else {
+                    self.partial.extend(chunk[..n].iter().cloned());
+                }
+            }
+
+            // Decode as many complete sequences as `partial` now holds.
+            let mut out = Vec::with_capacity(self.partial.len());
+            match decode_into(&mut out, &self.partial, self.java) {
+                Ok(()) => {
+                    self.base += self.partial.len();
+                    self.partial.clear();
+                }
+                Err(err) => {
+                    let good = err.valid_up_to();
+                    if err.error_len().is_none() && !self.eof {
+                        // A sequence was merely truncated by the chunk
+                        // boundary; keep its bytes and read more input.
+                        self.partial.drain(..good);
+                        self.base += good;
+                    } else {
+                        // A genuine malformed sequence, or a truncation at
+                        // the real end of input.  Remember it, re-based onto
+                        // the whole stream, and report it after the prefix.
+                        self.error = Some(Cesu8DecodingError {
+                            valid_up_to: self.base + good,
+                            error_len: err.error_len(),
+                        });
+                        self.partial.clear();
+                    }
+                }
+            }
+            self.decoded = out;
+            self.pos = 0;
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for Cesu8Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        try!(self.fill());
+        let n = cmp::min(buf.len(), self.decoded.len() - self.pos);
+        buf[..n].clone_from_slice(&self.decoded[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Return the length of the longest prefix of `bytes` that consists only of
+/// complete UTF-8 sequences, so that any trailing partial sequence can be
+/// held back until its remaining bytes arrive.
+fn complete_utf8_prefix(bytes: &[u8]) -> usize {
+    let mut i = 0;
+    while i < bytes.len() {
+        // A zero width means an invalid lead byte; treat it as a single byte
+        // so the UTF-8 validation downstream is the one to reject it.
+        let w = match utf8_char_width(bytes[i]) {
+            0 => 1,
+            w => w,
+        };
+        if i + w > bytes.len() { break; }
+        i += w;
+    }
+    i
+}
+
+/// An adapter that encodes UTF-8 bytes written to it as CESU-8 and forwards
+/// the result to an underlying [`Write`].  A UTF-8 sequence split across two
+/// `write` calls is buffered until its remaining bytes arrive, so the caller
+/// need not align its writes to character boundaries.
+pub struct Cesu8Writer<W: Write> {
+    inner: W,
+    java: bool,
+    // Trailing bytes of an incomplete UTF-8 sequence from a previous write.
+    partial: Vec<u8>,
+}
+
+impl<W: Write> Cesu8Writer<W> {
+    /// Create a writer which encodes standard CESU-8 to `inner`.
+    pub fn new(inner: W) -> Cesu8Writer<W> {
+        Cesu8Writer { inner: inner, java: false, partial: Vec::new() }
+    }
+
+    /// Create a writer which encodes Java's Modified UTF-8 to `inner`.
+    pub fn new_java(inner: W) -> Cesu8Writer<W> {
+        Cesu8Writer { java: true, ..Cesu8Writer::new(inner) }
+    }
+
+    /// Consume the writer, returning the underlying writer.  Returns an error
+    /// if an incomplete UTF-8 sequence is still buffered.
+    pub fn into_inner(self) -> io::Result<W> {
+        if self.partial.is_empty() {
+            Ok(self.inner)
+        } else {
+            Err(io::Error::new(io::ErrorKind::InvalidData,
+                               "incomplete trailing UTF-8 sequence"))
+        }
+    }
+}
+
+impl<W: Write> Write for Cesu8Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.partial.extend(buf.iter().cloned());
+        let complete = complete_utf8_prefix(&self.partial);
+        {
+            let text = match from_utf8(&self.partial[..complete]) {
+                Ok(text) => text,
+                Err(_) => return Err(io::Error::new(
+                    io::ErrorKind::InvalidData, "invalid UTF-8")),
+            };
+            let mut out = Vec::with_capacity(text.len());
+            encode_into(text.as_bytes(), self.java, &mut out);
+            try!(self.inner.write_all(&out));
+        }
+        self.partial.drain(..complete);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[test]
+fn test_cesu8_reader_split_surrogate() {
+    // A 6-byte surrogate pair split across the chunk boundary must still
+    // decode once the second half arrives.
+    let data = vec![0xED, 0xA0, 0x81, 0xED, 0xB0, 0x81];
+    let mut out = Vec::new();
+    // A reader whose `read` hands over one byte at a time exercises the
+    // straddling-boundary buffering.
+    let mut reader = Cesu8Reader::new(OneByteAtATime(&data[..], 0));
+    io::copy(&mut reader, &mut out).unwrap();
+    assert_eq!(&out[..], "\u{10401}".as_bytes());
+
+    // A genuinely malformed stream surfaces a positioned error.
+    let bad = vec![0x4D, 0xED, 0xA0, 0x81, 0x4D];
+    let mut reader = Cesu8Reader::new(&bad[..]);
+    let mut out = Vec::new();
+    assert!(io::copy(&mut reader, &mut out).is_err());
+}
+
+#[test]
+fn test_cesu8_writer_split_char() {
+    // A 4-byte UTF-8 character split across two writes is buffered and then
+    // encoded as a 6-byte surrogate pair.
+    let bytes = "\u{10401}".as_bytes().to_vec();
+    let mut writer = Cesu8Writer::new(Vec::new());
+    writer.write_all(&bytes[..2]).unwrap();
+    writer.write_all(&bytes[2..]).unwrap();
+    let out = writer.into_inner().unwrap();
+    assert_eq!(&out[..], &[0xED, 0xA0, 0x81, 0xED, 0xB0, 0x81]);
+}
+
+// A test reader that yields at most one byte per `read`, to force multi-byte
+// sequences to straddle fill boundaries.
+#[cfg(test)]
+struct OneByteAtATime<'a>(&'a [u8], usize);
+
+#[cfg(test)]
+impl<'a> Read for OneByteAtATime<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.1 >= self.0.len() || buf.is_empty() {
+            return Ok(0);
+        }
+        buf[0] = self.0[self.1];
+        self.1 += 1;
+        Ok(1)
+    }
 }